@@ -13,17 +13,20 @@ extern crate rusttype;
 #[macro_use]
 extern crate serde_derive;
 
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
 use std::fs::File;
-use std::io::{stdout, BufReader};
+use std::io::{stdout, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
 use std::path;
 
+use chrono::TimeZone;
 use docopt::Docopt;
 use gpx::read;
 use gpx::{Gpx, Track};
 use geo::Point;
-use palette::{Gradient, Hsv, IntoColor, RgbHue};
+use palette::{Gradient, Hsv, IntoColor, Rgb, RgbHue};
 use image::ImageBuffer;
 use imageproc::drawing::draw_text_mut;
 use rayon::prelude::*;
@@ -34,6 +37,7 @@ Generate video from GPX files.
 
 Usage:
   derivers -b BOUNDS [options] <directory>
+  derivers -b BOUNDS --source-url=URL [options]
   derivers (-h|--help)
 
 Arguments:
@@ -45,23 +49,39 @@ Options:
   -w, --width=WIDTH      Width of output, in pixels [default: 1920]
   --height=HEIGHT        Force height of output to pixel size (automatically calculated by default)
   -o, --output=FILE      Output a PNG of cumulative heatmap data to file. [default: heatmap.png]
+  --source-url=URL       Fetch a JSON manifest of GPX links from URL instead of reading a directory.
+  --token=TOKEN          Bearer token sent as the Authorization header when fetching.
+  --basic=USER:PASS      HTTP basic-auth credentials sent as the Authorization header when fetching.
+  --palette=FILE         Gradient stops, one per line: 'pos r g b' (RGB 0-255) or 'pos hsv h s v' (hue degrees, sat/val 0-1).
+  --background=R,G,B     Background color for empty cells [default: 0,0,0]
 
 Video options:
   -r, --frame-rate=RATE  Output a frame every `RATE` GPS points [default: 1500]
   -s, --ppm-stream       Output a PPM stream to stdout.
+  --sixel                Render frames as Sixel graphics to stdout.
+  --decay=AMOUNT         Subtract AMOUNT from every cell on a time cadence so old tracks fade.
+  --decay-interval=SECS  Seconds of activity time between decay steps [default: 86400]
   --title                Render activity title into each frame.
   --date                 Render activity date into each frame.
 ";
 
 #[derive(Debug, Deserialize)]
 struct CommandArgs {
-    arg_directory: String,
+    arg_directory: Option<String>,
     flag_bounds: String,
+    flag_source_url: Option<String>,
+    flag_token: Option<String>,
+    flag_basic: Option<String>,
     flag_frame_rate: u32,
     flag_height: Option<u32>,
     flag_help: bool,
     flag_output: String,
+    flag_palette: Option<String>,
+    flag_background: String,
     flag_ppm_stream: bool,
+    flag_sixel: bool,
+    flag_decay: Option<f32>,
+    flag_decay_interval: u32,
     flag_title: bool,
     flag_date: bool,
     flag_width: u32,
@@ -69,6 +89,76 @@ struct CommandArgs {
 
 type ScreenPoint = (u32, u32);
 
+// A single error type threaded through parsing, projection and rendering so
+// a malformed input produces a clear diagnostic and a nonzero exit code
+// rather than aborting the whole batch with a panic.
+#[derive(Debug)]
+enum DeriversError {
+    Io(std::io::Error),
+    Gpx(gpx::errors::Error),
+    BadBounds(String),
+    BadPalette(String),
+    BadBackground(String),
+    Http(String),
+    NoTracks,
+    NoPoints,
+    Render(image::ImageError),
+}
+
+impl std::fmt::Display for DeriversError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            DeriversError::Io(ref err) => write!(f, "{}", err),
+            DeriversError::Gpx(ref err) => write!(f, "{}", err),
+            DeriversError::BadBounds(ref bounds) => {
+                write!(f, "malformed bounds '{}', expected 'top left bottom right'", bounds)
+            }
+            DeriversError::BadPalette(ref msg) => write!(f, "malformed palette: {}", msg),
+            DeriversError::BadBackground(ref spec) => {
+                write!(f, "malformed background '{}', expected 'R,G,B'", spec)
+            }
+            DeriversError::Http(ref msg) => write!(f, "HTTP error: {}", msg),
+            DeriversError::NoTracks => write!(f, "file has no tracks"),
+            DeriversError::NoPoints => write!(f, "file has no track points"),
+            DeriversError::Render(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for DeriversError {
+    fn description(&self) -> &str {
+        match *self {
+            DeriversError::Io(ref err) => err.description(),
+            DeriversError::Gpx(ref err) => err.description(),
+            DeriversError::BadBounds(_) => "malformed bounds",
+            DeriversError::BadPalette(_) => "malformed palette",
+            DeriversError::BadBackground(_) => "malformed background",
+            DeriversError::Http(_) => "HTTP error",
+            DeriversError::NoTracks => "file has no tracks",
+            DeriversError::NoPoints => "file has no track points",
+            DeriversError::Render(ref err) => err.description(),
+        }
+    }
+}
+
+impl From<std::io::Error> for DeriversError {
+    fn from(err: std::io::Error) -> DeriversError {
+        DeriversError::Io(err)
+    }
+}
+
+impl From<gpx::errors::Error> for DeriversError {
+    fn from(err: gpx::errors::Error) -> DeriversError {
+        DeriversError::Gpx(err)
+    }
+}
+
+impl From<image::ImageError> for DeriversError {
+    fn from(err: image::ImageError) -> DeriversError {
+        DeriversError::Render(err)
+    }
+}
+
 lazy_static!{
     static ref GRADIENT: Gradient<Hsv<f64>> = {
         let stops = vec![
@@ -91,27 +181,121 @@ lazy_static!{
     };
 }
 
+// Load gradient stops from a palette file. Each non-empty line is a stop of
+// the form `position r g b` (RGB, 0-255) or, with an explicit color-space
+// keyword, `position hsv h s v` (hue in degrees, saturation/value 0-1); a
+// leading `rgb` keyword is also accepted for the default form. `#` begins a
+// comment. Because an unadorned triple is ambiguous between the two spaces,
+// HSV stops MUST carry the `hsv` keyword. Stops are placed at their given
+// positions along the ramp.
+fn load_palette(path: &str) -> Result<Gradient<Hsv<f64>>, DeriversError> {
+    let contents = fs::read_to_string(path)?;
+
+    let parse = |field: &str| -> Result<f64, DeriversError> {
+        field
+            .parse()
+            .map_err(|_| DeriversError::BadPalette(format!("bad number '{}'", field)))
+    };
+
+    let mut stops = vec![];
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(DeriversError::BadPalette(format!("too few fields: '{}'", line)));
+        }
+        let pos = parse(fields[0])?;
+
+        let color = if fields.len() >= 5 && fields[1].eq_ignore_ascii_case("hsv") {
+            let h = parse(fields[2])?;
+            let s = parse(fields[3])?;
+            let v = parse(fields[4])?;
+            Hsv::new(RgbHue::from(h), s, v)
+        } else {
+            // Skip an optional leading `rgb` keyword for the default form.
+            let base = if fields.len() >= 5 && fields[1].eq_ignore_ascii_case("rgb") {
+                2
+            } else {
+                1
+            };
+            if fields.len() < base + 3 {
+                return Err(DeriversError::BadPalette(format!("too few fields: '{}'", line)));
+            }
+            let r = parse(fields[base])?;
+            let g = parse(fields[base + 1])?;
+            let b = parse(fields[base + 2])?;
+            Hsv::from(Rgb::new(r / 255.0, g / 255.0, b / 255.0))
+        };
+
+        stops.push((pos, color));
+    }
+
+    // A gradient needs at least two stops to interpolate; fewer produces a
+    // degenerate ramp that panics at `get` time.
+    if stops.len() < 2 {
+        return Err(DeriversError::BadPalette(String::from(
+            "need at least two gradient stops",
+        )));
+    }
+
+    Ok(Gradient::with_domain(stops))
+}
+
+// Parse a `R,G,B` triple into byte channels.
+fn parse_background(spec: &str) -> Result<(u8, u8, u8), DeriversError> {
+    let parts: Result<Vec<u8>, _> = spec.split(',').map(|c| c.trim().parse()).collect();
+    let parts = parts.map_err(|_| DeriversError::BadBackground(spec.to_string()))?;
+    if parts.len() != 3 {
+        return Err(DeriversError::BadBackground(spec.to_string()));
+    }
+    Ok((parts[0], parts[1], parts[2]))
+}
+
 struct Heatmap {
     top_left: Point<f64>,
     bottom_right: Point<f64>,
     width: u32,
     height: u32,
-    heatmap: Vec<u32>,
-    max_value: u32,
+    heatmap: Vec<f32>,
+    max_value: f32,
     render_date: bool,
     render_title: bool,
+    gradient: Gradient<Hsv<f64>>,
+    background: (u8, u8, u8),
+}
+
+// Append a run of `len` copies of sixel char `ch`, using the `!<n>` repeat
+// introducer when it pays for itself. Used by `Heatmap::as_sixel`.
+fn flush_sixel_run(out: &mut Vec<u8>, ch: u8, len: u32) {
+    if len == 0 {
+        return;
+    }
+    if len > 3 {
+        out.extend_from_slice(format!("!{}", len).as_bytes());
+        out.push(ch);
+    } else {
+        for _ in 0..len {
+            out.push(ch);
+        }
+    }
 }
 
 impl Heatmap {
-    pub fn from(args: &CommandArgs) -> Heatmap {
-        let split_bounds = args.flag_bounds
-            .as_str()
-            .split(' ')
-            .map(|b| b.parse().unwrap())
-            .collect::<Vec<f64>>();
+    pub fn from(args: &CommandArgs) -> Result<Heatmap, DeriversError> {
+        let mut split_bounds = Vec::with_capacity(4);
+        for bound in args.flag_bounds.as_str().split(' ') {
+            let value = bound
+                .parse()
+                .map_err(|_| DeriversError::BadBounds(args.flag_bounds.clone()))?;
+            split_bounds.push(value);
+        }
 
         if split_bounds.len() != 4 {
-            panic!("Wrong format for boundaries!");
+            return Err(DeriversError::BadBounds(args.flag_bounds.clone()));
         }
 
         let top_left = Point::new(split_bounds[1], split_bounds[0]);
@@ -129,19 +313,24 @@ impl Heatmap {
 
         let mut heatmap = Vec::with_capacity(size);
         for _ in 0..size {
-            heatmap.push(0);
+            heatmap.push(0.0);
         }
 
-        Heatmap {
+        Ok(Heatmap {
             top_left: top_left,
             bottom_right: bot_right,
             width: width,
             height: height,
             heatmap: heatmap,
-            max_value: 0,
+            max_value: 0.0,
             render_date: args.flag_date,
             render_title: args.flag_title,
-        }
+            gradient: match args.flag_palette {
+                Some(ref path) => load_palette(path)?,
+                None => GRADIENT.clone(),
+            },
+            background: parse_background(&args.flag_background)?,
+        })
     }
 
     pub fn as_image(&self) -> image::DynamicImage {
@@ -149,13 +338,13 @@ impl Heatmap {
             .clone()
             .into_par_iter()
             .map(|count| {
-                if count == 0 {
-                    return (0, 0, 0);
+                if count == 0.0 {
+                    return self.background;
                 }
 
                 let heat = (count as f64).log(self.max_value as f64);
 
-                GRADIENT.get(heat).into_rgb().to_pixel()
+                self.gradient.get(heat).into_rgb().to_pixel()
             })
             .collect::<Vec<_>>();
 
@@ -192,8 +381,99 @@ impl Heatmap {
         image
     }
 
+    // Quantize the rendered RGB image into a small indexed palette and encode
+    // it as Sixel graphics so accumulation can be watched in a Sixel-capable
+    // terminal. Colors are reduced to a 6x6x6 cube whose registers are all
+    // defined once up front; within each band only the registers that
+    // actually appear have pixel selections emitted.
+    pub fn as_sixel(&self) -> Vec<u8> {
+        let rgb = self.as_image().to_rgb();
+        let (width, height) = (self.width as usize, self.height as usize);
+        let raw = rgb.into_raw();
+
+        let quant = |c: u8| -> u8 { ((c as u32 * 5 + 127) / 255) as u8 };
+        let index_at = |x: usize, y: usize| -> u8 {
+            let i = (y * width + x) * 3;
+            quant(raw[i]) * 36 + quant(raw[i + 1]) * 6 + quant(raw[i + 2])
+        };
+
+        let mut out = Vec::new();
+        // DCS q: start sixel, default raster aspect ratio.
+        out.extend_from_slice(b"\x1bPq");
+
+        // Emit a color register for every cube entry (0..216); a level `l`
+        // maps back to l/5 of full scale, expressed 0..100 for sixel.
+        for idx in 0..216u16 {
+            let r = (idx / 36) % 6;
+            let g = (idx / 6) % 6;
+            let b = idx % 6;
+            out.extend_from_slice(
+                format!(
+                    "#{};2;{};{};{}",
+                    idx,
+                    r as u32 * 20,
+                    g as u32 * 20,
+                    b as u32 * 20
+                ).as_bytes(),
+            );
+        }
+
+        let mut band = 0;
+        while band < height {
+            let rows = (height - band).min(6);
+
+            // Which color indexes appear anywhere in this band.
+            let mut present = [false; 216];
+            for y in 0..rows {
+                for x in 0..width {
+                    present[index_at(x, band + y) as usize] = true;
+                }
+            }
+
+            for idx in 0..216usize {
+                if !present[idx] {
+                    continue;
+                }
+
+                out.extend_from_slice(format!("#{}", idx).as_bytes());
+
+                // Run-length encode a row of sixel chars for this color.
+                let mut run_char = 0u8;
+                let mut run_len = 0u32;
+                for x in 0..width {
+                    let mut bits = 0u8;
+                    for y in 0..rows {
+                        if index_at(x, band + y) as usize == idx {
+                            bits |= 1 << y;
+                        }
+                    }
+                    let ch = 0x3f + bits;
+                    if run_len > 0 && ch == run_char {
+                        run_len += 1;
+                    } else {
+                        flush_sixel_run(&mut out, run_char, run_len);
+                        run_char = ch;
+                        run_len = 1;
+                    }
+                }
+                flush_sixel_run(&mut out, run_char, run_len);
+
+                // Carriage return to overlay the next color on the same band.
+                out.push(b'$');
+            }
+
+            // Next band.
+            out.push(b'-');
+            band += 6;
+        }
+
+        // ST: terminate the sixel sequence.
+        out.extend_from_slice(b"\x1b\\");
+        out
+    }
+
     #[inline]
-    fn get_pixel_mut(&mut self, point: &ScreenPoint) -> Option<&mut u32> {
+    fn get_pixel_mut(&mut self, point: &ScreenPoint) -> Option<&mut f32> {
         if point.0 >= self.width || point.1 >= self.height {
             return None;
         }
@@ -207,22 +487,104 @@ impl Heatmap {
         // FIXME: lol rust?
         let px = {
             let px = self.get_pixel_mut(point).unwrap();
-            *px += 1;
+            *px += 1.0;
             *px
         };
 
         self.max_value = self.max_value.max(px);
     }
 
-    #[allow(dead_code)]
-    pub fn decay(&mut self, amount: u32) {
-        self.max_value -= 1;
+    // Rasterize the line between two successive projected points using
+    // Xiaolin Wu's algorithm: step one pixel along the major axis and
+    // distribute coverage between the two pixels straddling the true
+    // sub-pixel position on the minor axis. Only the open interval between
+    // the endpoints is covered — the vertices themselves are deposited once
+    // each by `add_point`, so shared endpoints of adjacent segments don't
+    // accumulate two or three times.
+    pub fn add_segment(&mut self, a: &ScreenPoint, b: &ScreenPoint) {
+        let (ax, ay) = (a.0 as f64, a.1 as f64);
+        let (bx, by) = (b.0 as f64, b.1 as f64);
+
+        let dx = bx - ax;
+        let dy = by - ay;
+
+        // Accumulate this segment's coverage per cell first so a segment that
+        // revisits a cell adds at most 1.0 to it — vello's nonzero-winding
+        // `min(abs(area), 1.0)` rule — rather than spiking `max_value`.
+        let mut coverage: HashMap<usize, f32> = HashMap::new();
+        {
+            let (width, height) = (self.width, self.height);
+            let mut deposit = |xi: i64, yi: i64, w: f32| {
+                if w <= 0.0 || xi < 0 || yi < 0 {
+                    return;
+                }
+                let (x, y) = (xi as u32, yi as u32);
+                if x >= width || y >= height {
+                    return;
+                }
+                let index = (x + y * width) as usize;
+                let cell = coverage.entry(index).or_insert(0.0);
+                *cell = (*cell + w).min(1.0);
+            };
+
+            if dx.abs() >= dy.abs() {
+                // x is the major axis.
+                let (x0, y0, x1) = if ax <= bx { (ax, ay, bx) } else { (bx, by, ax) };
+                let gradient = if dx == 0.0 { 0.0 } else { dy / dx };
+
+                let mut x = x0 as i64 + 1;
+                let end = x1 as i64;
+                while x < end {
+                    let y = y0 + gradient * (x as f64 - x0);
+                    let yfloor = y.floor();
+                    let frac = (y - yfloor) as f32;
+                    let yi = yfloor as i64;
+
+                    deposit(x, yi, 1.0 - frac);
+                    deposit(x, yi + 1, frac);
+
+                    x += 1;
+                }
+            } else {
+                // y is the major axis.
+                let (x0, y0, y1) = if ay <= by { (ax, ay, by) } else { (bx, by, ay) };
+                let gradient = dx / dy;
+
+                let mut y = y0 as i64 + 1;
+                let end = y1 as i64;
+                while y < end {
+                    let x = x0 + gradient * (y as f64 - y0);
+                    let xfloor = x.floor();
+                    let frac = (x - xfloor) as f32;
+                    let xi = xfloor as i64;
+
+                    deposit(xi, y, 1.0 - frac);
+                    deposit(xi + 1, y, frac);
+
+                    y += 1;
+                }
+            }
+        }
+
+        for (index, weight) in coverage {
+            let px = self.heatmap[index] + weight;
+            self.heatmap[index] = px;
+            self.max_value = self.max_value.max(px);
+        }
+    }
 
+    // Subtract `amount` from every cell (clamped at zero) so older tracks
+    // fade over time, then recompute the true running maximum so the color
+    // ramp in `as_image` stays correctly normalized.
+    pub fn decay(&mut self, amount: f32) {
         self.heatmap.par_iter_mut().for_each(|px| {
-            if *px > amount {
-                *px -= amount;
-            }
+            *px = (*px - amount).max(0.0);
         });
+
+        self.max_value = self.heatmap
+            .par_iter()
+            .cloned()
+            .reduce(|| 0.0, f32::max);
     }
 
     // Using simple equirectangular projection for now. Returns None if point
@@ -255,15 +617,17 @@ struct Activity {
     track_points: Vec<Point<f64>>,
 }
 
-fn parse_gpx(path: &path::PathBuf) -> Result<Activity, Box<Error>> {
+fn parse_gpx(path: &path::PathBuf) -> Result<Activity, DeriversError> {
     let file = File::open(path)?;
-    let reader = BufReader::new(file);
+    parse_gpx_reader(BufReader::new(file))
+}
 
+fn parse_gpx_reader<R: Read>(reader: R) -> Result<Activity, DeriversError> {
     let gpx: Gpx = read(reader)?;
 
     // Nothing to do if there are no tracks
     if gpx.tracks.len() == 0 {
-        return Err(Box::from("file has no tracks"));
+        return Err(DeriversError::NoTracks);
     } else if gpx.tracks.len() > 1 {
         eprintln!("Warning! more than 1 track, just taking first");
     }
@@ -289,12 +653,277 @@ fn parse_gpx(path: &path::PathBuf) -> Result<Activity, Box<Error>> {
     }
 
     if activity.track_points.len() == 0 {
-        Err(Box::from("No track points"))
+        Err(DeriversError::NoPoints)
     } else {
         Ok(activity)
     }
 }
 
+// Action cameras and dashcams embed their GPS track in a custom `gps ` box:
+// an 8-byte header, a u64 version/date, then a table of fixed 8-byte
+// descriptors, each a `{ offset: u32, size: u32 }` pointing at a GPS data
+// block elsewhere in the file. We walk the top-level box list to find it,
+// read the table, and decode each referenced block into samples.
+fn read_be_u32(buf: &[u8], at: usize) -> u32 {
+    ((buf[at] as u32) << 24)
+        | ((buf[at + 1] as u32) << 16)
+        | ((buf[at + 2] as u32) << 8)
+        | (buf[at + 3] as u32)
+}
+
+fn read_be_u64(buf: &[u8], at: usize) -> u64 {
+    ((read_be_u32(buf, at) as u64) << 32) | (read_be_u32(buf, at + 4) as u64)
+}
+
+fn read_be_f64(buf: &[u8], at: usize) -> f64 {
+    f64::from_bits(read_be_u64(buf, at))
+}
+
+// One descriptor in the `gps ` box table.
+struct GpsChunk {
+    offset: u32,
+    size: u32,
+}
+
+fn parse_mp4(path: &path::PathBuf) -> Result<Activity, DeriversError> {
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+
+    // Walk the top-level box list looking for `gps `. Each box is a 4-byte
+    // big-endian size (including the header) followed by a 4-byte type.
+    let mut header = [0u8; 8];
+    let mut gps_box: Option<(u64, u64)> = None;
+    let mut pos = 0u64;
+    while file.read(&mut header)? == header.len() {
+        let size = read_be_u32(&header, 0) as u64;
+        if size < header.len() as u64 {
+            break;
+        }
+
+        if &header[4..8] == b"gps " {
+            gps_box = Some((pos + header.len() as u64, size - header.len() as u64));
+            break;
+        }
+
+        pos += size;
+        file.seek(SeekFrom::Start(pos))?;
+    }
+
+    let (body_start, body_len) = match gps_box {
+        Some(b) => b,
+        None => return Err(DeriversError::NoTracks),
+    };
+
+    // Box sizes come from the file itself and are untrusted; refuse to
+    // allocate a body that cannot fit in the file rather than risk OOM.
+    if body_len > file_len || body_start + body_len > file_len {
+        return Err(DeriversError::NoTracks);
+    }
+
+    // Box body (after the 8-byte box header): u64 version/date, then the
+    // descriptor table.
+    file.seek(SeekFrom::Start(body_start))?;
+    let mut body = vec![0u8; body_len as usize];
+    file.read_exact(&mut body)?;
+
+    if body.len() < 8 {
+        return Err(DeriversError::NoTracks);
+    }
+
+    let date_word = read_be_u64(&body, 0);
+    let mut chunks = vec![];
+    let mut at = 8;
+    while at + 8 <= body.len() {
+        chunks.push(GpsChunk {
+            offset: read_be_u32(&body, at),
+            size: read_be_u32(&body, at + 4),
+        });
+        at += 8;
+    }
+
+    // Each referenced block is a run of samples: u64 timestamp, f64 lat,
+    // f64 lng (24 bytes each).
+    let mut activity = Activity {
+        name: path.file_stem()
+            .and_then(|s| s.to_str())
+            .map(String::from)
+            .unwrap_or(String::from("Untitled")),
+        // A garbage date word can be out of range; fall back to "now".
+        date: chrono::Utc
+            .timestamp_opt(date_word as i64, 0)
+            .single()
+            .unwrap_or_else(chrono::Utc::now),
+        track_points: vec![],
+    };
+
+    for chunk in chunks.iter() {
+        // Skip descriptors that point outside the file instead of allocating
+        // an arbitrary (up to 4 GiB) buffer for them.
+        if chunk.offset as u64 + chunk.size as u64 > file_len {
+            continue;
+        }
+
+        file.seek(SeekFrom::Start(chunk.offset as u64))?;
+        let mut block = vec![0u8; chunk.size as usize];
+        file.read_exact(&mut block)?;
+
+        let mut at = 0;
+        while at + 24 <= block.len() {
+            let lat = read_be_f64(&block, at + 8);
+            let lng = read_be_f64(&block, at + 16);
+            activity.track_points.push(Point::new(lng, lat));
+            at += 24;
+        }
+    }
+
+    if activity.track_points.len() == 0 {
+        Err(DeriversError::NoPoints)
+    } else {
+        Ok(activity)
+    }
+}
+
+// Standard base64 alphabet, used to encode basic-auth credentials.
+fn base64_encode(input: &[u8]) -> String {
+    const ALPHABET: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::new();
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let triple = (b[0] as u32) << 16 | (b[1] as u32) << 8 | (b[2] as u32);
+
+        out.push(ALPHABET[(triple >> 18 & 0x3f) as usize] as char);
+        out.push(ALPHABET[(triple >> 12 & 0x3f) as usize] as char);
+        out.push(if chunk.len() > 1 {
+            ALPHABET[(triple >> 6 & 0x3f) as usize] as char
+        } else {
+            '='
+        });
+        out.push(if chunk.len() > 2 {
+            ALPHABET[(triple & 0x3f) as usize] as char
+        } else {
+            '='
+        });
+    }
+    out
+}
+
+// Decode an HTTP/1.1 `Transfer-Encoding: chunked` body into its raw bytes.
+// Chunk sizes come from the server and are not trusted, so a size that runs
+// past the end of the buffer stops decoding rather than panicking.
+fn decode_chunked(body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut at = 0;
+    while at < body.len() {
+        let line_end = match find_subslice(&body[at..], b"\r\n") {
+            Some(i) => at + i,
+            None => break,
+        };
+        let size_str = String::from_utf8_lossy(&body[at..line_end]);
+        let size = usize::from_str_radix(size_str.trim(), 16).unwrap_or(0);
+        at = line_end + 2;
+        if size == 0 || at + size > body.len() {
+            break;
+        }
+        out.extend_from_slice(&body[at..at + size]);
+        at += size + 2; // skip the trailing CRLF
+    }
+    out
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+// Minimal HTTP/1.1 GET over a plain TCP connection. `auth` is a ready-made
+// `Authorization` header value (e.g. `Bearer <token>`), if any. Only the
+// `http://` scheme is supported: there is no TLS stack here, so `https://`
+// URLs are rejected up front rather than silently connecting in cleartext.
+fn http_get(url: &str, auth: &Option<String>) -> Result<Vec<u8>, DeriversError> {
+    let without_scheme = match url.splitn(2, "://").collect::<Vec<_>>().as_slice() {
+        ["http", rest] => *rest,
+        ["https", _] => {
+            return Err(DeriversError::Http(format!("https is not supported (no TLS): {}", url)))
+        }
+        _ => return Err(DeriversError::Http(format!("malformed URL: {}", url))),
+    };
+
+    let (host_port, path) = match without_scheme.find('/') {
+        Some(i) => (&without_scheme[..i], &without_scheme[i..]),
+        None => (without_scheme, "/"),
+    };
+    let (host, port) = match host_port.find(':') {
+        Some(i) => (&host_port[..i], host_port[i + 1..].parse().unwrap_or(80)),
+        None => (host_port, 80u16),
+    };
+
+    let mut stream = TcpStream::connect((host, port))?;
+
+    let mut request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nAccept: */*\r\n",
+        path, host
+    );
+    if let Some(value) = auth.as_ref() {
+        request.push_str(&format!("Authorization: {}\r\n", value));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw)?;
+
+    let header_end = find_subslice(&raw, b"\r\n\r\n")
+        .ok_or_else(|| DeriversError::Http(String::from("malformed response")))?;
+
+    // Reject non-2xx responses so auth failures and redirects don't get fed
+    // to the GPX parser as if they were track data.
+    let status_end = find_subslice(&raw, b"\r\n").unwrap_or(header_end);
+    let status_line = String::from_utf8_lossy(&raw[..status_end]);
+    let status: u32 = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| DeriversError::Http(format!("malformed status line: {}", status_line)))?;
+    if status < 200 || status >= 300 {
+        return Err(DeriversError::Http(format!("server returned status {}", status)));
+    }
+
+    let headers = String::from_utf8_lossy(&raw[..header_end]).to_lowercase();
+    let body = &raw[header_end + 4..];
+
+    if headers.contains("transfer-encoding: chunked") {
+        Ok(decode_chunked(body))
+    } else {
+        Ok(body.to_vec())
+    }
+}
+
+// Pull every `http`/`https` link out of a manifest document. We only need
+// the GPX URLs, so a quoted-string scan keeps us independent of the exact
+// JSON shape the endpoint returns.
+fn extract_links(body: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(body);
+    let mut links = vec![];
+    let mut rest = text.as_ref();
+    while let Some(start) = rest.find('"') {
+        rest = &rest[start + 1..];
+        if let Some(end) = rest.find('"') {
+            let candidate = &rest[..end];
+            if candidate.starts_with("http://") || candidate.starts_with("https://") {
+                links.push(candidate.to_string());
+            }
+            rest = &rest[end + 1..];
+        } else {
+            break;
+        }
+    }
+    links
+}
+
 fn main() {
     let args: CommandArgs = Docopt::new(USAGE)
         .and_then(|d| d.deserialize())
@@ -305,6 +934,13 @@ fn main() {
         return;
     }
 
+    if let Err(err) = run(args) {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: CommandArgs) -> Result<(), DeriversError> {
     let is_tty = unsafe { libc::isatty(libc::STDOUT_FILENO as i32) } != 0;
     if args.flag_ppm_stream && is_tty {
         eprintln!(
@@ -313,62 +949,159 @@ Please pipe output to a file or program."
         );
         std::process::exit(1);
     }
+    if args.flag_sixel && !is_tty {
+        eprintln!("Refusing to write sixel data to a non-TTY.");
+        std::process::exit(1);
+    }
 
-    let mut map = Heatmap::from(&args);
-    let output_dir = match fs::read_dir(args.arg_directory) {
-        Ok(dir) => dir,
-        Err(err) => {
-            eprintln!("Error reading input directory: {}", err);
-            std::process::exit(1);
-        }
-    };
+    let mut map = Heatmap::from(&args)?;
+
+    // Bearer takes precedence over basic when both are supplied.
+    let auth = args.flag_token
+        .as_ref()
+        .map(|t| format!("Bearer {}", t))
+        .or_else(|| {
+            args.flag_basic
+                .as_ref()
+                .map(|c| format!("Basic {}", base64_encode(c.as_bytes())))
+        });
+
+    let mut activities: Vec<Activity> = if let Some(ref source_url) = args.flag_source_url {
+        let manifest = match http_get(source_url, &auth) {
+            Ok(body) => body,
+            Err(err) => {
+                eprintln!("Error fetching manifest: {}", err);
+                std::process::exit(1);
+            }
+        };
 
-    let paths: Vec<path::PathBuf> = output_dir.into_iter().map(|p| p.unwrap().path()).collect();
+        let links = extract_links(&manifest);
+        eprint!("Fetching {:?} remote GPX files...", links.len());
 
-    eprint!("Parsing {:?} GPX files...", paths.len());
+        links
+            .into_par_iter()
+            .filter_map(|url| match http_get(&url, &auth) {
+                Ok(bytes) => match parse_gpx_reader(BufReader::new(&bytes[..])) {
+                    Ok(activity) => Some(activity),
+                    Err(err) => {
+                        eprintln!("Skipping {}: {}", url, err);
+                        None
+                    }
+                },
+                Err(err) => {
+                    eprintln!("Skipping {}: {}", url, err);
+                    None
+                }
+            })
+            .collect()
+    } else {
+        let directory = args.arg_directory.clone().unwrap_or_default();
+        let output_dir = fs::read_dir(directory)?;
+
+        let paths: Vec<path::PathBuf> = output_dir
+            .into_iter()
+            .filter_map(|p| p.ok().map(|entry| entry.path()))
+            .collect();
 
-    let mut activities: Vec<Activity> = paths
-        .into_par_iter()
-        .filter_map(|ref p| parse_gpx(p).ok())
-        .collect();
+        eprint!("Parsing {:?} GPX files...", paths.len());
+
+        paths
+            .into_par_iter()
+            .filter_map(|ref p| {
+                let result = match p.extension().and_then(|e| e.to_str()) {
+                    Some("mp4") | Some("MP4") => parse_mp4(p),
+                    _ => parse_gpx(p),
+                };
+                match result {
+                    Ok(activity) => Some(activity),
+                    Err(err) => {
+                        eprintln!("Skipping {:?}: {}", p, err);
+                        None
+                    }
+                }
+            })
+            .collect()
+    };
 
     activities.sort_by_key(|a| a.date);
 
     eprintln!("Done!");
 
-    let png_file = &mut File::create(args.flag_output).unwrap();
+    let png_file = &mut File::create(&args.flag_output)?;
     let mut stdout = stdout();
 
+    let decay_interval = chrono::Duration::seconds(args.flag_decay_interval as i64);
+    // A zero/negative interval would never advance the decay anchor, so only
+    // decay when a positive cadence is configured.
+    let decay_amount = if decay_interval > chrono::Duration::zero() {
+        args.flag_decay
+    } else {
+        None
+    };
+    let mut decay_anchor: Option<chrono::DateTime<chrono::Utc>> = None;
+
     let mut counter;
     for act in activities {
         eprintln!("Activity: {}", act.name);
 
+        // Fade older activity out on a time cadence driven by the gap between
+        // this activity and the last decay step.
+        if let Some(amount) = decay_amount {
+            match decay_anchor {
+                None => decay_anchor = Some(act.date),
+                Some(mut anchor) => {
+                    while act.date - anchor >= decay_interval {
+                        map.decay(amount);
+                        anchor = anchor + decay_interval;
+                    }
+                    decay_anchor = Some(anchor);
+                }
+            }
+        }
+
         let pixels: Vec<ScreenPoint> = act.track_points
             .par_iter()
             .filter_map(|ref pt| map.project_to_screen(pt))
             .collect();
 
         counter = 0;
-        for ref point in pixels.into_iter() {
+        for (i, point) in pixels.iter().enumerate() {
             map.add_point(point);
 
+            // Connect consecutive samples so sparse tracks render as paths
+            // rather than isolated pixels.
+            if i > 0 {
+                let prev = &pixels[i - 1];
+                map.add_segment(prev, point);
+            }
+
             counter += 1;
 
             if counter % args.flag_frame_rate == 0 {
                 if args.flag_ppm_stream {
                     let image = map.as_image_with_overlay(&act);
-                    image.save(&mut stdout, image::PPM).unwrap();
+                    image.save(&mut stdout, image::PPM)?;
+                }
+                if args.flag_sixel {
+                    // Move the cursor home and repaint in place.
+                    stdout.write_all(b"\x1b[H")?;
+                    stdout.write_all(&map.as_sixel())?;
+                    stdout.flush()?;
                 }
             }
         }
-
-        // FIXME: this is pretty ugly.
-        // map.decay(1);
     }
 
     if args.flag_ppm_stream {
-        map.as_image().save(&mut stdout, image::PPM).unwrap();
+        map.as_image().save(&mut stdout, image::PPM)?;
+    };
+    if args.flag_sixel {
+        stdout.write_all(b"\x1b[H")?;
+        stdout.write_all(&map.as_sixel())?;
+        stdout.flush()?;
     };
 
-    map.as_image().save(png_file, image::PNG).unwrap();
+    map.as_image().save(png_file, image::PNG)?;
+
+    Ok(())
 }